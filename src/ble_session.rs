@@ -0,0 +1,224 @@
+// BLE session task
+//
+// Consumes freshly (re)connected vehicle links from the reconnection
+// supervisor, runs the VCSEC/INFOTAINMENT session handshake over each one
+// via `session::SessionManager`, then signs and transmits dispatched and
+// polled commands over the link and forwards decoded vehicle replies to
+// whoever is listening (the polling scheduler's state-publishing path).
+// Everything sent to or parsed from the vehicle here is a
+// `proto::universal_message::RoutableMessage` envelope, built and consumed
+// by `SessionManager`; this task only owns framing it onto the BLE link.
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use log::{info, warn};
+use prost::Message;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::bluetooth::VehicleLink;
+use crate::dispatch::VehicleCommand;
+use crate::proto::universal_message::{self, Domain};
+use crate::session::SessionManager;
+
+/// How long we give the user to tap their key card on the vehicle after
+/// sending a whitelist enrollment request, before giving up and retrying on
+/// the next reconnect.
+const KEY_CARD_TAP_WINDOW: Duration = Duration::from_secs(30);
+
+/// How long we wait for a vehicle reply to a session handshake request
+/// before giving up and retrying on the next reconnect. Unlike
+/// `KEY_CARD_TAP_WINDOW`, no physical action is required here, so this is
+/// much shorter.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The payload of an inbound `RoutableMessage` from the vehicle, unwrapped
+/// by [`decode_reply`] but not yet decoded into a specific response type.
+/// Handed off to whoever interprets replies (e.g. the polling state
+/// publisher, which tries each known response type in turn).
+pub struct VehicleReply {
+    pub payload: Vec<u8>,
+}
+
+/// Runs forever: waits for a connected link from the supervisor, performs
+/// the session handshake for both command domains, then relays signed
+/// commands out and raw replies back until the link drops — at which point
+/// it waits for the supervisor to hand over the next one.
+pub async fn run(
+    mut links: mpsc::UnboundedReceiver<VehicleLink>,
+    mut commands: mpsc::Receiver<VehicleCommand>,
+    replies: mpsc::Sender<VehicleReply>,
+    vin: String,
+    session_state_path: String,
+    poll_now: broadcast::Sender<()>,
+) {
+    while let Some(mut link) = links.recv().await {
+        info!("BLE session task received a fresh link to {}", link.address());
+
+        let mut session_manager = match SessionManager::load_or_init(&session_state_path) {
+            Ok(manager) => manager,
+            Err(e) => {
+                warn!("Failed to load session state, will retry on next link: {}", e);
+                continue;
+            }
+        };
+
+        if !session_manager.is_enrolled() {
+            if let Err(e) = enroll(&mut session_manager, &mut link, &session_state_path).await {
+                warn!("Vehicle key enrollment failed: {}", e);
+                continue;
+            }
+        }
+
+        if let Err(e) = handshake(&mut session_manager, &mut link, Domain::DomainVehicleSecurity).await
+        {
+            warn!("VCSEC session handshake failed: {}", e);
+            continue;
+        }
+        if let Err(e) = handshake(&mut session_manager, &mut link, Domain::DomainInfotainment).await {
+            warn!("INFOTAINMENT session handshake failed: {}", e);
+            continue;
+        }
+        info!("Session established with vehicle, ready to send commands");
+        if let Err(e) = session_manager.persist(&session_state_path) {
+            warn!("Failed to persist session state: {}", e);
+        }
+        // Force every polled signal to refresh now rather than leaving HA
+        // entities on stale state until their next scheduled tick.
+        let _ = poll_now.send(());
+
+        loop {
+            tokio::select! {
+                maybe_command = commands.recv() => {
+                    let Some(command) = maybe_command else {
+                        info!("Command channel closed, ending BLE session task");
+                        return;
+                    };
+                    if let Err(e) = send_command(&mut session_manager, &mut link, &vin, command).await {
+                        warn!("Failed to send command to vehicle: {}", e);
+                        continue;
+                    }
+                    if let Err(e) = session_manager.persist(&session_state_path) {
+                        warn!("Failed to persist session state: {}", e);
+                    }
+                }
+                maybe_frame = link.incoming().next() => {
+                    let Some(frame) = maybe_frame else {
+                        info!("Vehicle link closed, waiting for reconnect");
+                        break;
+                    };
+                    let Some(reply) = decode_reply(&frame) else {
+                        warn!("Could not decode inbound frame as a RoutableMessage, dropping it");
+                        continue;
+                    };
+                    if replies.send(reply).await.is_err() {
+                        warn!("No receiver for vehicle replies, dropping frame");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Add this add-on's public key to the vehicle's VCSEC whitelist. The
+/// vehicle only accepts this while a physical key card is tapped on it, so
+/// this prompts the user and waits up to [`KEY_CARD_TAP_WINDOW`] for its
+/// acknowledgement before giving up.
+async fn enroll(
+    session_manager: &mut SessionManager,
+    link: &mut VehicleLink,
+    session_state_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    info!(
+        "Vehicle key not yet enrolled. Tap your key card on the vehicle within {:?} to pair it.",
+        KEY_CARD_TAP_WINDOW
+    );
+
+    let enrollment = session_manager.build_whitelist_enrollment();
+    link.send(&enrollment.encode_to_vec()).await?;
+
+    // We don't yet have a session to verify the reply's signature, so this
+    // only confirms the vehicle sent something addressed to us, not that the
+    // whitelist add specifically succeeded.
+    recv_addressed_reply(session_manager, link, KEY_CARD_TAP_WINDOW).await?;
+
+    info!("Vehicle acknowledged whitelist enrollment");
+    session_manager.mark_enrolled();
+    session_manager.persist(session_state_path)
+}
+
+/// Request `SessionInfo` for `domain` and establish the session from the
+/// reply: send a `RoutableMessage` carrying our `session_info_request`, wait
+/// for the vehicle's `RoutableMessage` reply, and hand its `session_info`
+/// payload to `SessionManager::establish_from_reply`.
+async fn handshake(
+    session_manager: &mut SessionManager,
+    link: &mut VehicleLink,
+    domain: Domain,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let request = session_manager.build_session_info_request(domain);
+    link.send(&request.encode_to_vec()).await?;
+
+    let reply = recv_addressed_reply(session_manager, link, HANDSHAKE_TIMEOUT).await?;
+    session_manager.establish_from_reply(domain, &reply)
+}
+
+/// Read inbound frames off `link` until one decodes as a `RoutableMessage`
+/// addressed to us, ignoring anything else (undecodable noise, or frames
+/// addressed elsewhere), or `timeout` elapses.
+async fn recv_addressed_reply(
+    session_manager: &SessionManager,
+    link: &mut VehicleLink,
+    timeout: Duration,
+) -> Result<universal_message::RoutableMessage, Box<dyn std::error::Error>> {
+    let wait_for_reply = async {
+        loop {
+            let frame = link
+                .incoming()
+                .next()
+                .await
+                .ok_or("link closed before a reply arrived")?;
+            let Ok(reply) = universal_message::RoutableMessage::decode(frame.as_slice()) else {
+                continue;
+            };
+            if !session_manager.is_addressed_to_us(&reply) {
+                continue;
+            }
+
+            return Ok::<_, Box<dyn std::error::Error>>(reply);
+        }
+    };
+
+    tokio::time::timeout(timeout, wait_for_reply)
+        .await
+        .map_err(|_| "timed out waiting for a reply")?
+}
+
+async fn send_command(
+    session_manager: &mut SessionManager,
+    link: &mut VehicleLink,
+    vin: &str,
+    command: VehicleCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (domain, payload) = encode_command(&command);
+    let signed = session_manager.sign(domain, vin, &payload)?;
+
+    link.send(&signed.encode_to_vec()).await
+}
+
+/// Unwrap an inbound `RoutableMessage` frame down to the response payload
+/// callers actually care about. Replies are not re-encrypted the way signed
+/// commands are, so `protobuf_message_as_bytes` is the plaintext response.
+fn decode_reply(frame: &[u8]) -> Option<VehicleReply> {
+    let routable = universal_message::RoutableMessage::decode(frame).ok()?;
+    Some(VehicleReply {
+        payload: routable.protobuf_message_as_bytes,
+    })
+}
+
+fn encode_command(command: &VehicleCommand) -> (Domain, Vec<u8>) {
+    match command {
+        VehicleCommand::Vcsec(message) => (Domain::DomainVehicleSecurity, message.encode_to_vec()),
+        VehicleCommand::CarServer(action) => (Domain::DomainInfotainment, action.encode_to_vec()),
+    }
+}
@@ -1,7 +1,30 @@
 // Bluetooth Low Energy module using bluer crate
 
-use log::{debug, info};
-use bluer::Adapter;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bluer::gatt::remote::{Characteristic, Service};
+use bluer::{Adapter, Address, Device};
+use futures::stream::{Stream, StreamExt};
+use log::{debug, info, warn};
+use sha1::{Digest, Sha1};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Tesla vehicle GATT service and characteristic UUIDs.
+///
+/// See https://github.com/teslamotors/vehicle-command/blob/main/pkg/ble/const.go
+const VEHICLE_SERVICE_UUID: uuid::Uuid = uuid::uuid!("00000211-b2d1-43f0-9b88-960cebf8b91e");
+const TO_VEHICLE_CHAR_UUID: uuid::Uuid = uuid::uuid!("00000212-b2d1-43f0-9b88-960cebf8b91e");
+const FROM_VEHICLE_CHAR_UUID: uuid::Uuid = uuid::uuid!("00000213-b2d1-43f0-9b88-960cebf8b91e");
+
+/// Conservative default ATT MTU payload size. The real MTU is negotiated on
+/// connect, but Tesla vehicles are observed to accept writes no larger than
+/// this without negotiation, so we chunk to it unconditionally.
+const DEFAULT_CHUNK_SIZE: usize = 20;
+
+/// Length of the frame length-prefix, in bytes.
+const FRAME_PREFIX_LEN: usize = 2;
 
 pub struct BleAdapter {
     adapter: Adapter,
@@ -31,17 +54,261 @@ impl BleAdapter {
         &self.name
     }
 
-    // TODO: Implement BLE scanning
-    pub async fn scan_for_devices(&self) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Starting BLE device scan...");
-        // Placeholder for scanning implementation
-        Ok(())
+    /// Scan for the configured vehicle and return a handle to it.
+    ///
+    /// Filters advertisements down to the Tesla vehicle service UUID and
+    /// matches the resulting candidates against the local name derived from
+    /// `vin` (see [`expected_local_name`]), since several vehicles may be
+    /// advertising the same service within range.
+    pub async fn scan_for_devices(
+        &self,
+        vin: &str,
+    ) -> Result<DiscoveredVehicle, Box<dyn std::error::Error>> {
+        let expected_name = expected_local_name(vin);
+        info!(
+            "Starting BLE device scan for vehicle local name: {}",
+            expected_name
+        );
+
+        let filter = bluer::DiscoveryFilter {
+            uuids: [VEHICLE_SERVICE_UUID].into_iter().collect(),
+            transport: bluer::DiscoveryTransport::Le,
+            ..Default::default()
+        };
+        self.adapter.set_discovery_filter(filter).await?;
+
+        let mut events = self.adapter.discover_devices().await?;
+        let timeout = tokio::time::sleep(Duration::from_secs(30));
+        tokio::pin!(timeout);
+
+        loop {
+            tokio::select! {
+                Some(evt) = events.next() => {
+                    let bluer::AdapterEvent::DeviceAdded(addr) = evt else { continue };
+                    let device = self.adapter.device(addr)?;
+                    match device.name().await {
+                        Ok(Some(name)) if name == expected_name => {
+                            info!("Found vehicle {} at {}", expected_name, addr);
+                            return Ok(DiscoveredVehicle { address: addr });
+                        }
+                        Ok(Some(name)) => debug!("Ignoring advertisement from {}: {}", addr, name),
+                        _ => {}
+                    }
+                }
+                _ = &mut timeout => {
+                    return Err(format!("timed out waiting for vehicle {}", expected_name).into());
+                }
+            }
+        }
     }
 
-    // TODO: Implement device connection
-    pub async fn connect_to_device(&self, address: &str) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Connecting to device: {}", address);
-        // Placeholder for connection implementation
+    /// Look up the bluer `Device` handle for a known address, e.g. to watch
+    /// its connection-state events without doing a full rescan.
+    pub fn device(&self, address: Address) -> Result<Device, Box<dyn std::error::Error>> {
+        Ok(self.adapter.device(address)?)
+    }
+
+    /// Connect to a previously discovered vehicle and resolve its GATT
+    /// characteristics, returning a [`VehicleLink`] ready to exchange
+    /// `RoutableMessage` frames.
+    pub async fn connect_to_device(
+        &self,
+        vehicle: &DiscoveredVehicle,
+    ) -> Result<VehicleLink, Box<dyn std::error::Error>> {
+        info!("Connecting to device: {}", vehicle.address);
+        let device = self.adapter.device(vehicle.address)?;
+
+        if !device.is_connected().await? {
+            device.connect().await?;
+        }
+
+        let (write_char, notify_char) = resolve_characteristics(&device).await?;
+
+        let notify_stream = notify_char.notify().await?;
+        let incoming = spawn_reassembler(notify_stream);
+
+        Ok(VehicleLink {
+            address: vehicle.address,
+            write_char,
+            incoming,
+        })
+    }
+}
+
+/// A vehicle matched during scanning, identified by its BLE address.
+#[derive(Debug, Clone, Copy)]
+pub struct DiscoveredVehicle {
+    pub address: Address,
+}
+
+/// An open GATT link to a vehicle's Tesla vehicle service, able to send and
+/// receive framed `RoutableMessage` payloads.
+pub struct VehicleLink {
+    address: Address,
+    write_char: Characteristic,
+    incoming: ReceiverStream<Vec<u8>>,
+}
+
+impl VehicleLink {
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Send a `RoutableMessage` payload to the vehicle, framed with a 2-byte
+    /// big-endian length prefix and chunked to [`DEFAULT_CHUNK_SIZE`] bytes
+    /// per write, as required by the vehicle's BLE GATT transport.
+    pub async fn send(&self, payload: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if payload.len() > u16::MAX as usize {
+            return Err("payload too large to frame".into());
+        }
+
+        let mut frame = Vec::with_capacity(FRAME_PREFIX_LEN + payload.len());
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(payload);
+
+        for chunk in frame.chunks(DEFAULT_CHUNK_SIZE) {
+            self.write_char.write(chunk).await?;
+        }
+
         Ok(())
     }
+
+    /// Stream of fully reassembled inbound `RoutableMessage` payloads.
+    pub fn incoming(&mut self) -> &mut (impl Stream<Item = Vec<u8>> + Unpin) {
+        &mut self.incoming
+    }
+}
+
+async fn resolve_characteristics(
+    device: &Device,
+) -> Result<(Characteristic, Characteristic), Box<dyn std::error::Error>> {
+    let services = device.services().await?;
+    let mut by_uuid: HashMap<uuid::Uuid, Service> = HashMap::new();
+    for service in services {
+        by_uuid.insert(service.uuid().await?, service);
+    }
+
+    let service = by_uuid
+        .get(&VEHICLE_SERVICE_UUID)
+        .ok_or("vehicle did not advertise the Tesla vehicle service")?;
+
+    let mut write_char = None;
+    let mut notify_char = None;
+    for characteristic in service.characteristics().await? {
+        let uuid = characteristic.uuid().await?;
+        if uuid == TO_VEHICLE_CHAR_UUID {
+            write_char = Some(characteristic);
+        } else if uuid == FROM_VEHICLE_CHAR_UUID {
+            notify_char = Some(characteristic);
+        }
+    }
+
+    let write_char = write_char.ok_or("vehicle service missing write characteristic")?;
+    let notify_char = notify_char.ok_or("vehicle service missing notify characteristic")?;
+
+    Ok((write_char, notify_char))
+}
+
+/// Spawn a task that reassembles length-prefixed frames out of a raw
+/// notification stream and forwards complete frames on the returned stream.
+fn spawn_reassembler(
+    mut notify_stream: impl Stream<Item = Vec<u8>> + Unpin + Send + 'static,
+) -> ReceiverStream<Vec<u8>> {
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut expected_len: Option<usize> = None;
+
+        while let Some(fragment) = notify_stream.next().await {
+            buffer.extend_from_slice(&fragment);
+
+            loop {
+                if expected_len.is_none() && buffer.len() >= FRAME_PREFIX_LEN {
+                    let len = u16::from_be_bytes([buffer[0], buffer[1]]) as usize;
+                    expected_len = Some(len);
+                }
+
+                let Some(len) = expected_len else { break };
+                if buffer.len() < FRAME_PREFIX_LEN + len {
+                    break;
+                }
+
+                let frame: Vec<u8> = buffer
+                    .drain(..FRAME_PREFIX_LEN + len)
+                    .skip(FRAME_PREFIX_LEN)
+                    .collect();
+                expected_len = None;
+
+                if tx.send(frame).await.is_err() {
+                    warn!("vehicle notification receiver dropped, stopping reassembler");
+                    return;
+                }
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// Derive the BLE local name a vehicle advertises for a given VIN: the first
+/// 8 bytes of SHA-1(vin), lowercase hex-encoded, wrapped as `S<16 hex>C`.
+pub fn expected_local_name(vin: &str) -> String {
+    let digest = Sha1::digest(vin.as_bytes());
+    let prefix = &digest[..8];
+    let hex: String = prefix.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("S{}C", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    #[test]
+    fn local_name_has_expected_shape() {
+        let name = expected_local_name("5YJSA1E2XJF000001");
+        assert_eq!(name.len(), 18);
+        assert!(name.starts_with('S'));
+        assert!(name.ends_with('C'));
+        assert!(name[1..17].chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn local_name_is_deterministic_per_vin() {
+        assert_eq!(
+            expected_local_name("5YJSA1E2XJF000001"),
+            expected_local_name("5YJSA1E2XJF000001")
+        );
+        assert_ne!(
+            expected_local_name("5YJSA1E2XJF000001"),
+            expected_local_name("5YJSA1E2XJF000002")
+        );
+    }
+
+    #[tokio::test]
+    async fn reassembler_reassembles_a_frame_split_across_fragments() {
+        // A single 2-byte frame ("hi") split mid-payload across two
+        // notifications must come out as one reassembled frame.
+        let fragments = vec![vec![0x00, 0x02, b'h'], vec![b'i']];
+        let notify_stream = stream::iter(fragments);
+
+        let mut frames = spawn_reassembler(notify_stream);
+        let frame = frames.next().await.expect("expected one reassembled frame");
+
+        assert_eq!(frame, b"hi");
+    }
+
+    #[tokio::test]
+    async fn reassembler_splits_multiple_frames_in_one_fragment() {
+        // Two complete frames ("ab", "c") delivered in a single notification
+        // must be emitted as two separate frames, in order.
+        let fragment = vec![0x00, 0x02, b'a', b'b', 0x00, 0x01, b'c'];
+        let notify_stream = stream::iter(vec![fragment]);
+
+        let mut frames = spawn_reassembler(notify_stream);
+
+        assert_eq!(frames.next().await, Some(b"ab".to_vec()));
+        assert_eq!(frames.next().await, Some(b"c".to_vec()));
+    }
 }
@@ -32,6 +32,42 @@ pub struct BluetoothConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VehicleConfig {
     pub vin: String,
+    #[serde(default)]
+    pub polling: PollingConfig,
+}
+
+/// Declarative polling schedule for vehicle state signals, mirroring how a
+/// register-mapping bridge lets users declare which values to read and how
+/// often. Missing from older configs, `#[serde(default)]` falls back to a
+/// sane default set so existing installs keep working.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollingConfig {
+    #[serde(default = "default_signals")]
+    pub signals: Vec<SignalConfig>,
+    /// Floor applied to every signal's configured interval, so a typo'd
+    /// short interval can't hammer a sleeping vehicle.
+    #[serde(default = "default_min_interval_secs")]
+    pub min_interval_secs: u64,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        PollingConfig {
+            signals: default_signals(),
+            min_interval_secs: default_min_interval_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalConfig {
+    /// Matches the discovery entity's object_id, e.g. "battery_level".
+    pub name: String,
+    pub interval_secs: u64,
+    /// Waking a sleeping vehicle costs battery, so most signals default to
+    /// not polling while asleep.
+    #[serde(default)]
+    pub poll_while_asleep: bool,
 }
 
 fn default_port() -> u16 {
@@ -42,6 +78,50 @@ fn default_discovery_prefix() -> String {
     "homeassistant".to_string()
 }
 
+fn default_min_interval_secs() -> u64 {
+    30
+}
+
+fn default_signals() -> Vec<SignalConfig> {
+    vec![
+        SignalConfig {
+            name: "battery_level".to_string(),
+            interval_secs: 60,
+            poll_while_asleep: false,
+        },
+        SignalConfig {
+            name: "range".to_string(),
+            interval_secs: 300,
+            poll_while_asleep: false,
+        },
+        SignalConfig {
+            name: "charge_state".to_string(),
+            interval_secs: 60,
+            poll_while_asleep: false,
+        },
+        SignalConfig {
+            name: "interior_temp".to_string(),
+            interval_secs: 120,
+            poll_while_asleep: false,
+        },
+        SignalConfig {
+            name: "charging".to_string(),
+            interval_secs: 60,
+            poll_while_asleep: false,
+        },
+        SignalConfig {
+            name: "locked".to_string(),
+            interval_secs: 120,
+            poll_while_asleep: false,
+        },
+        SignalConfig {
+            name: "asleep".to_string(),
+            interval_secs: 300,
+            poll_while_asleep: true,
+        },
+    ]
+}
+
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
     let config_path = "/config/options.json";
     let config_content = fs::read_to_string(config_path)?;
@@ -0,0 +1,174 @@
+// Home Assistant MQTT discovery entity definitions
+//
+// Builds the set of HA entities we register for a vehicle: sensors, binary
+// sensors, and command entities (switches/buttons/numbers). Each entity is
+// described declaratively here and turned into an MQTT discovery payload by
+// `MqttClient::publish_all_discovery`.
+
+use serde_json::{json, Value};
+
+/// MQTT component type, as used in the discovery topic
+/// `<prefix>/<component>/<object_id>/config`.
+#[derive(Debug, Clone, Copy)]
+pub enum Component {
+    Sensor,
+    BinarySensor,
+    Switch,
+    Button,
+    Number,
+}
+
+impl Component {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Component::Sensor => "sensor",
+            Component::BinarySensor => "binary_sensor",
+            Component::Switch => "switch",
+            Component::Button => "button",
+            Component::Number => "number",
+        }
+    }
+}
+
+/// A single entity to register with Home Assistant.
+pub struct EntityDescriptor {
+    pub component: Component,
+    /// Suffix used both for the unique_id and for the command/state topic,
+    /// e.g. "battery_level" or "lock".
+    pub object_id: &'static str,
+    pub name: &'static str,
+    /// Extra discovery config fields specific to this entity (device_class,
+    /// unit_of_measurement, min/max/step, payloads, etc).
+    pub extra: Value,
+    pub has_command_topic: bool,
+}
+
+/// The full set of entities published for every vehicle.
+pub fn vehicle_entities() -> Vec<EntityDescriptor> {
+    vec![
+        EntityDescriptor {
+            component: Component::Sensor,
+            object_id: "battery_level",
+            name: "Battery Level",
+            extra: json!({
+                "device_class": "battery",
+                "unit_of_measurement": "%",
+                "state_class": "measurement",
+            }),
+            has_command_topic: false,
+        },
+        EntityDescriptor {
+            component: Component::Sensor,
+            object_id: "range",
+            name: "Range",
+            extra: json!({
+                "unit_of_measurement": "mi",
+                "state_class": "measurement",
+                "icon": "mdi:map-marker-distance",
+            }),
+            has_command_topic: false,
+        },
+        EntityDescriptor {
+            component: Component::Sensor,
+            object_id: "charge_state",
+            name: "Charge State",
+            extra: json!({ "icon": "mdi:ev-station" }),
+            has_command_topic: false,
+        },
+        EntityDescriptor {
+            component: Component::Sensor,
+            object_id: "interior_temp",
+            name: "Interior Temperature",
+            extra: json!({
+                "device_class": "temperature",
+                "unit_of_measurement": "°C",
+                "state_class": "measurement",
+            }),
+            has_command_topic: false,
+        },
+        EntityDescriptor {
+            component: Component::BinarySensor,
+            object_id: "charging",
+            name: "Charging",
+            extra: json!({ "device_class": "battery_charging" }),
+            has_command_topic: false,
+        },
+        EntityDescriptor {
+            component: Component::BinarySensor,
+            object_id: "locked",
+            name: "Locked",
+            extra: json!({ "device_class": "lock" }),
+            has_command_topic: false,
+        },
+        EntityDescriptor {
+            component: Component::BinarySensor,
+            object_id: "asleep",
+            name: "Asleep",
+            extra: json!({ "icon": "mdi:sleep" }),
+            has_command_topic: false,
+        },
+        EntityDescriptor {
+            component: Component::Switch,
+            object_id: "lock",
+            name: "Lock",
+            extra: json!({
+                "payload_on": "LOCK",
+                "payload_off": "UNLOCK",
+                "icon": "mdi:car-door-lock",
+            }),
+            has_command_topic: true,
+        },
+        EntityDescriptor {
+            component: Component::Switch,
+            object_id: "charge",
+            name: "Charging Switch",
+            extra: json!({
+                "payload_on": "START",
+                "payload_off": "STOP",
+                "icon": "mdi:ev-station",
+            }),
+            has_command_topic: true,
+        },
+        EntityDescriptor {
+            component: Component::Switch,
+            object_id: "climate",
+            name: "Climate",
+            extra: json!({
+                "payload_on": "ON",
+                "payload_off": "OFF",
+                "icon": "mdi:fan",
+            }),
+            has_command_topic: true,
+        },
+        EntityDescriptor {
+            component: Component::Button,
+            object_id: "trunk",
+            name: "Trunk",
+            extra: json!({ "icon": "mdi:car-back" }),
+            has_command_topic: true,
+        },
+        EntityDescriptor {
+            component: Component::Number,
+            object_id: "charge_limit",
+            name: "Charge Limit",
+            extra: json!({
+                "unit_of_measurement": "%",
+                "min": 50,
+                "max": 100,
+                "step": 1,
+                "icon": "mdi:battery-charging-100",
+            }),
+            has_command_topic: true,
+        },
+    ]
+}
+
+/// Build the `device` block shared by every discovery payload for a vehicle,
+/// so Home Assistant groups all of its entities under one device.
+pub fn device_block(vin: &str) -> Value {
+    json!({
+        "identifiers": [vin],
+        "name": format!("Tesla {}", vin),
+        "manufacturer": "Tesla",
+    })
+}
@@ -0,0 +1,263 @@
+// Command dispatcher: routes MQTT command topics to Tesla BLE commands.
+//
+// Mirrors the connector/dispatch pattern of an MQTT bridge: each supported
+// command is a row in a table keyed by its command-topic suffix, mapping the
+// raw MQTT payload to a protobuf request. The dispatcher owns an mpsc
+// channel to the BLE session task so MQTT callbacks never block the event
+// loop, and publishes a command-result ack back to a status topic.
+
+use log::{info, warn};
+use tokio::sync::mpsc;
+
+use crate::proto::car_server;
+use crate::proto::vcsec;
+
+/// A command decoded off an MQTT command topic, ready to hand to the BLE
+/// session task for signing and transmission.
+#[derive(Debug)]
+pub enum VehicleCommand {
+    Vcsec(vcsec::ToVCSECMessage),
+    CarServer(car_server::Action),
+}
+
+/// Builds a [`VehicleCommand`] from a command topic's raw MQTT payload.
+type Builder = fn(&[u8]) -> Result<VehicleCommand, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The set of supported commands. Adding a new command only requires a new
+/// row here plus its builder function below.
+fn command_table() -> &'static [(&'static str, Builder)] {
+    &[
+        ("lock", build_lock),
+        ("trunk", build_trunk),
+        ("charge_limit", build_charge_limit),
+        ("charge", build_charge),
+        ("climate", build_climate),
+    ]
+}
+
+/// Result of dispatching a single command, published back to the status
+/// topic as an ack.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CommandResult {
+    pub object_id: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Owns the channel to the BLE session task and the table of supported
+/// commands. Constructed once at startup and driven by [`Dispatcher::handle`]
+/// for every incoming MQTT publish on a command topic.
+pub struct Dispatcher {
+    to_session: mpsc::Sender<VehicleCommand>,
+}
+
+impl Dispatcher {
+    pub fn new(to_session: mpsc::Sender<VehicleCommand>) -> Self {
+        Dispatcher { to_session }
+    }
+
+    /// Handle one MQTT publish on a command topic of the form
+    /// `<vin>/<object_id>/set`, dispatching it to the matching table entry.
+    pub async fn handle(&self, topic: &str, payload: &[u8]) -> CommandResult {
+        let object_id = topic
+            .rsplit('/')
+            .nth(1)
+            .unwrap_or(topic)
+            .to_string();
+
+        let entry = command_table()
+            .iter()
+            .find(|(suffix, _)| object_id.ends_with(suffix));
+
+        let Some((_, builder)) = entry else {
+            warn!("No command registered for topic: {}", topic);
+            return CommandResult {
+                object_id,
+                ok: false,
+                detail: "unsupported command".to_string(),
+            };
+        };
+
+        match builder(payload) {
+            Ok(command) => match self.to_session.send(command).await {
+                Ok(()) => {
+                    info!("Dispatched command for {}", object_id);
+                    CommandResult {
+                        object_id,
+                        ok: true,
+                        detail: "queued".to_string(),
+                    }
+                }
+                Err(_) => CommandResult {
+                    object_id,
+                    ok: false,
+                    detail: "BLE session task is not running".to_string(),
+                },
+            },
+            Err(e) => {
+                warn!("Failed to build command for {}: {}", object_id, e);
+                CommandResult {
+                    object_id,
+                    ok: false,
+                    detail: e.to_string(),
+                }
+            }
+        }
+    }
+}
+
+fn build_lock(payload: &[u8]) -> Result<VehicleCommand, Box<dyn std::error::Error + Send + Sync>> {
+    let action = match payload {
+        b"LOCK" => vcsec::RkeAction::RkeActionLock,
+        b"UNLOCK" => vcsec::RkeAction::RkeActionUnlock,
+        other => return Err(format!("unknown lock payload: {:?}", other).into()),
+    };
+
+    Ok(VehicleCommand::Vcsec(vcsec::ToVCSECMessage {
+        signed_message: None,
+        unsigned_message: None,
+        rke_action: Some(action as i32),
+    }))
+}
+
+fn build_charge(payload: &[u8]) -> Result<VehicleCommand, Box<dyn std::error::Error + Send + Sync>> {
+    use car_server::vehicle_action::VehicleActionMsg;
+
+    let charging_action = match payload {
+        b"START" => car_server::ChargingStartStopAction {
+            charging_action: Some(car_server::charging_start_stop_action::ChargingAction::Start(
+                car_server::Void {},
+            )),
+        },
+        b"STOP" => car_server::ChargingStartStopAction {
+            charging_action: Some(car_server::charging_start_stop_action::ChargingAction::Stop(
+                car_server::Void {},
+            )),
+        },
+        other => return Err(format!("unknown charge payload: {:?}", other).into()),
+    };
+
+    Ok(VehicleCommand::CarServer(wrap_vehicle_action(
+        VehicleActionMsg::ChargingStartStopAction(charging_action),
+    )))
+}
+
+fn build_trunk(payload: &[u8]) -> Result<VehicleCommand, Box<dyn std::error::Error + Send + Sync>> {
+    use car_server::vehicle_action::VehicleActionMsg;
+
+    match payload {
+        b"PRESS" => Ok(VehicleCommand::CarServer(wrap_vehicle_action(
+            VehicleActionMsg::ActuateTrunk(car_server::ActuateTrunk {
+                which_trunk: car_server::actuate_trunk::TrunkType::Rear as i32,
+            }),
+        ))),
+        other => Err(format!("unknown trunk payload: {:?}", other).into()),
+    }
+}
+
+fn build_climate(payload: &[u8]) -> Result<VehicleCommand, Box<dyn std::error::Error + Send + Sync>> {
+    use car_server::vehicle_action::VehicleActionMsg;
+
+    let power_on = match payload {
+        b"ON" => true,
+        b"OFF" => false,
+        other => return Err(format!("unknown climate payload: {:?}", other).into()),
+    };
+
+    Ok(VehicleCommand::CarServer(wrap_vehicle_action(
+        VehicleActionMsg::HvacAutoAction(car_server::HvacAutoAction { power_on }),
+    )))
+}
+
+/// Charge limit entity declares a 50-100% range in `discovery.rs`; reject
+/// anything outside it here rather than forwarding an out-of-range value to
+/// the vehicle.
+const CHARGE_LIMIT_MIN_PERCENT: i32 = 50;
+const CHARGE_LIMIT_MAX_PERCENT: i32 = 100;
+
+fn build_charge_limit(
+    payload: &[u8],
+) -> Result<VehicleCommand, Box<dyn std::error::Error + Send + Sync>> {
+    use car_server::vehicle_action::VehicleActionMsg;
+
+    let percent: i32 = std::str::from_utf8(payload)?
+        .trim()
+        .parse()
+        .map_err(|_| "charge_limit payload must be an integer percentage")?;
+
+    if !(CHARGE_LIMIT_MIN_PERCENT..=CHARGE_LIMIT_MAX_PERCENT).contains(&percent) {
+        return Err(format!(
+            "charge_limit {} out of range {}-{}",
+            percent, CHARGE_LIMIT_MIN_PERCENT, CHARGE_LIMIT_MAX_PERCENT
+        )
+        .into());
+    }
+
+    Ok(VehicleCommand::CarServer(wrap_vehicle_action(
+        VehicleActionMsg::ChargingSetLimitAction(car_server::ChargingSetLimitAction {
+            percent,
+        }),
+    )))
+}
+
+fn wrap_vehicle_action(msg: car_server::vehicle_action::VehicleActionMsg) -> car_server::Action {
+    car_server::Action {
+        action_msg: Some(car_server::action::ActionMsg::VehicleAction(
+            car_server::VehicleAction {
+                vehicle_action_msg: Some(msg),
+            },
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn routes_to_matching_suffix_and_queues_command() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let dispatcher = Dispatcher::new(tx);
+
+        let result = dispatcher.handle("5YJ_lock/set", b"LOCK").await;
+
+        assert!(result.ok);
+        assert_eq!(result.object_id, "5YJ_lock");
+        assert!(matches!(rx.recv().await, Some(VehicleCommand::Vcsec(_))));
+    }
+
+    #[tokio::test]
+    async fn charge_limit_suffix_is_not_shadowed_by_charge() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let dispatcher = Dispatcher::new(tx);
+
+        let result = dispatcher.handle("5YJ_charge_limit/set", b"80").await;
+
+        assert!(result.ok, "{:?}", result);
+        assert!(matches!(
+            rx.recv().await,
+            Some(VehicleCommand::CarServer(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn charge_limit_out_of_range_is_rejected() {
+        let (tx, _rx) = mpsc::channel(1);
+        let dispatcher = Dispatcher::new(tx);
+
+        let result = dispatcher.handle("5YJ_charge_limit/set", b"10").await;
+
+        assert!(!result.ok);
+    }
+
+    #[tokio::test]
+    async fn unknown_topic_is_unsupported() {
+        let (tx, _rx) = mpsc::channel(1);
+        let dispatcher = Dispatcher::new(tx);
+
+        let result = dispatcher.handle("5YJ_frunk/set", b"PRESS").await;
+
+        assert!(!result.ok);
+        assert_eq!(result.detail, "unsupported command");
+    }
+}
@@ -1,13 +1,22 @@
 // Tesla BLE Local Control for Home Assistant
 // Work in Progress - Not yet functional
 
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
 use log::info;
 use tokio::signal;
 
+mod ble_session;
 mod bluetooth;
+mod discovery;
+mod dispatch;
 mod mqtt;
 mod proto;
 mod config;
+mod polling;
+mod session;
+mod supervisor;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -25,12 +34,24 @@ async fn main() -> anyhow::Result<()> {
     info!("Vehicle VIN: {}", config.vehicle.vin);
 
     // Initialize MQTT client
-    let mqtt_client = mqtt::MqttClient::new(config.mqtt)
-        .await
-        .expect("Failed to initialize MQTT client");
+    let (mqtt_client, mut incoming_commands) =
+        mqtt::MqttClient::new(config.mqtt, &config.vehicle.vin)
+            .await
+            .expect("Failed to initialize MQTT client");
+    let mqtt_client = Arc::new(mqtt_client);
 
     info!("MQTT client connected to {}", mqtt_client.host());
 
+    // Register HA entities for this vehicle
+    mqtt_client
+        .publish_all_discovery(&config.vehicle.vin)
+        .await
+        .expect("Failed to publish MQTT discovery config");
+    mqtt_client
+        .subscribe_all_commands(&config.vehicle.vin)
+        .await
+        .expect("Failed to subscribe to command topics");
+
     // Initialize BLE adapter
     let ble_adapter = bluetooth::BleAdapter::new(config.bluetooth.adapter)
         .await
@@ -38,11 +59,90 @@ async fn main() -> anyhow::Result<()> {
 
     info!("BLE adapter initialized: {}", ble_adapter.name());
 
-    // TODO: Implement vehicle discovery
-    // TODO: Implement vehicle pairing flow
-    // TODO: Implement MQTT entity publishing
-    // TODO: Implement vehicle state monitoring
-    // TODO: Implement command execution
+    // Dispatch incoming MQTT commands to the BLE session task.
+    let (to_session_tx, to_session_rx) = tokio::sync::mpsc::channel(16);
+    let dispatcher = dispatch::Dispatcher::new(to_session_tx.clone());
+
+    // Poll vehicle state signals on their configured cadence, feeding
+    // requests into the same BLE session channel as dispatched commands.
+    // `is_asleep` reflects the last decoded VCSEC status, updated by the
+    // state-publisher task below. `poll_now` lets the BLE session task force
+    // every signal to refresh immediately after a reconnect instead of
+    // waiting for its next scheduled tick.
+    let is_asleep = Arc::new(AtomicBool::new(false));
+    let (poll_now_tx, _) = tokio::sync::broadcast::channel(4);
+    // Subscribed here, synchronously, rather than inside the spawned
+    // scheduler task: a receiver must exist before the BLE session task's
+    // first post-handshake `poll_now.send(())` or that signal is dropped.
+    let poll_now_receivers: Vec<_> = config
+        .vehicle
+        .polling
+        .signals
+        .iter()
+        .map(|_| poll_now_tx.subscribe())
+        .collect();
+    let scheduler = polling::PollingScheduler::new(config.vehicle.polling.clone(), to_session_tx);
+    let scheduler_is_asleep = is_asleep.clone();
+    tokio::spawn(async move {
+        scheduler.run(scheduler_is_asleep, poll_now_receivers).await;
+    });
+
+    // The BLE session task owns pairing, handshake, and signing; it receives
+    // freshly (re)connected links from the supervisor below, enrolling the
+    // add-on's key with the vehicle on first connection if it hasn't been
+    // already, and raw vehicle replies are handed to `vehicle_replies_rx` for
+    // decoding/publishing.
+    let (links_tx, links_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (vehicle_replies_tx, vehicle_replies_rx) = tokio::sync::mpsc::channel(16);
+    let vin = config.vehicle.vin.clone();
+    tokio::spawn(ble_session::run(
+        links_rx,
+        to_session_rx,
+        vehicle_replies_tx,
+        vin,
+        session::DEFAULT_SESSION_STATE_PATH.to_string(),
+        poll_now_tx,
+    ));
+
+    // Decode vehicle replies into entity state and publish each signal's
+    // current value to its MQTT state topic.
+    let state_mqtt = mqtt_client.clone();
+    let vin = config.vehicle.vin.clone();
+    tokio::spawn(polling::run_state_publisher(
+        vehicle_replies_rx,
+        state_mqtt,
+        vin,
+        is_asleep,
+    ));
+
+    let vin = config.vehicle.vin.clone();
+    let status_mqtt = mqtt_client.clone();
+    tokio::spawn(async move {
+        while let Some(incoming) = incoming_commands.recv().await {
+            let result = dispatcher.handle(&incoming.topic, &incoming.payload).await;
+            let status_topic = format!("{}/status", vin);
+            let payload = serde_json::to_string(&result).unwrap_or_default();
+            if let Err(e) = status_mqtt.publish_state(&status_topic, &payload).await {
+                log::warn!("Failed to publish command result: {}", e);
+            }
+        }
+    });
+
+    // Supervise the BLE link: scan for the vehicle, reconnect with backoff
+    // on disconnect, and keep HA availability in sync with the link state.
+    // Each freshly established link is handed to the BLE session task,
+    // which runs the handshake and takes over sending/receiving on it.
+    let supervisor_mqtt = mqtt_client.clone();
+    let vin = config.vehicle.vin.clone();
+    tokio::spawn(async move {
+        let mut supervisor = supervisor::VehicleSupervisor::new(ble_adapter, vin);
+        supervisor
+            .run(&supervisor_mqtt, |link| {
+                info!("Vehicle link established: {}", link.address());
+                let _ = links_tx.send(link);
+            })
+            .await;
+    });
 
     info!("Setup complete. Waiting for vehicle...");
 
@@ -1,8 +1,23 @@
 // MQTT client module using rumqttc
 
-use log::{debug, info};
-use rumqttc::{AsyncClient, MqttOptions, QoS, Event, Incoming};
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, Event, Incoming, LastWill, MqttOptions, QoS};
+use serde_json::json;
+use tokio::sync::mpsc;
+
 use crate::config::MqttConfig;
+use crate::discovery::{device_block, vehicle_entities};
+
+/// An inbound publish on a subscribed command topic, handed off to the
+/// command dispatcher.
+pub struct IncomingCommand {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Payloads published on the availability topic, matched by the Last Will.
+pub const AVAILABLE_ONLINE: &str = "online";
+pub const AVAILABLE_OFFLINE: &str = "offline";
 
 pub struct MqttClient {
     client: AsyncClient,
@@ -10,15 +25,32 @@ pub struct MqttClient {
 }
 
 impl MqttClient {
-    pub async fn new(config: MqttConfig) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Connect to the broker and start its event loop. Returns the client
+    /// plus a receiver of inbound publishes on any topic subscribed via
+    /// [`Self::subscribe_to_commands`], so the caller can drive a dispatcher
+    /// without blocking this event loop.
+    pub async fn new(
+        config: MqttConfig,
+        vin: &str,
+    ) -> Result<(Self, mpsc::Receiver<IncomingCommand>), Box<dyn std::error::Error>> {
         let mut mqttoptions = MqttOptions::new("hass-tesla-ble", &config.host, config.port);
-        
+
         if let Some(username) = &config.username {
             mqttoptions.set_credentials(username, config.password.as_deref().unwrap_or(""));
         }
 
+        // Publish `offline` for this vehicle if we disconnect uncleanly, so
+        // entities grey out in HA instead of showing stale state.
+        mqttoptions.set_last_will(LastWill::new(
+            availability_topic(&config, vin),
+            AVAILABLE_OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+
         info!("Connecting to MQTT broker: {}:{}", config.host, config.port);
         let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
+        let (commands_tx, commands_rx) = mpsc::channel(32);
 
         // Start event loop in background
         tokio::spawn(async move {
@@ -27,6 +59,15 @@ impl MqttClient {
                     Ok(Event::Incoming(Incoming::ConnAck(_))) => {
                         info!("MQTT Connected");
                     }
+                    Ok(Event::Incoming(Incoming::Publish(publish))) => {
+                        let command = IncomingCommand {
+                            topic: publish.topic,
+                            payload: publish.payload.to_vec(),
+                        };
+                        if commands_tx.send(command).await.is_err() {
+                            warn!("command dispatcher dropped, discarding inbound publish");
+                        }
+                    }
                     Ok(notification) => {
                         debug!("MQTT event: {:?}", notification);
                     }
@@ -38,32 +79,90 @@ impl MqttClient {
             }
         });
 
-        Ok(MqttClient {
-            client,
-            config,
-        })
+        let mqtt_client = MqttClient { client, config };
+        mqtt_client.publish_availability(vin, AVAILABLE_ONLINE).await?;
+
+        Ok((mqtt_client, commands_rx))
     }
 
     pub fn host(&self) -> &str {
         &self.config.host
     }
 
-    // TODO: Implement Home Assistant MQTT discovery
-    pub async fn publish_discovery(
+    /// Publish `online`/`offline` to the vehicle's availability topic,
+    /// retained so Home Assistant sees the current state immediately on
+    /// subscribe.
+    pub async fn publish_availability(
+        &self,
+        vin: &str,
+        state: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let topic = availability_topic(&self.config, vin);
+        info!("Publishing availability to {}: {}", topic, state);
+        self.client
+            .publish(topic, QoS::AtLeastOnce, true, state)
+            .await?;
+        Ok(())
+    }
+
+    /// Register every HA entity for `vin`: sensors, binary sensors, and
+    /// command entities, each grouped under a shared `device` block so Home
+    /// Assistant shows them as one device. Discovery configs are retained so
+    /// entities survive a broker restart.
+    pub async fn publish_all_discovery(&self, vin: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let device = device_block(vin);
+        let availability_topic = availability_topic(&self.config, vin);
+
+        for entity in vehicle_entities() {
+            let object_id = format!("{}_{}", vin, entity.object_id);
+            let state_topic = self.state_topic(vin, entity.object_id);
+
+            let mut config_value = json!({
+                "name": entity.name,
+                "unique_id": object_id,
+                "state_topic": state_topic,
+                "availability_topic": availability_topic,
+                "device": device,
+            });
+
+            if entity.has_command_topic {
+                config_value["command_topic"] = json!(self.command_topic(vin, entity.object_id));
+            }
+
+            for (key, value) in entity.extra.as_object().into_iter().flatten() {
+                config_value[key] = value.clone();
+            }
+
+            self.publish_discovery(entity.component.as_str(), &object_id, config_value)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn state_topic(&self, vin: &str, object_id: &str) -> String {
+        format!("{}/{}/state", vin, object_id)
+    }
+
+    pub fn command_topic(&self, vin: &str, object_id: &str) -> String {
+        format!("{}/{}/set", vin, object_id)
+    }
+
+    async fn publish_discovery(
         &self,
         component: &str,
-        name: &str,
+        object_id: &str,
         config_value: serde_json::Value,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let topic = format!(
             "{}/{}/{}/config",
-            self.config.discovery_prefix, component, name
+            self.config.discovery_prefix, component, object_id
         );
         let payload = serde_json::to_string(&config_value)?;
 
         info!("Publishing discovery config to: {}", topic);
         self.client
-            .publish(topic, QoS::AtLeastOnce, false, payload)
+            .publish(topic, QoS::AtLeastOnce, true, payload)
             .await?;
 
         Ok(())
@@ -83,10 +182,26 @@ impl MqttClient {
         Ok(())
     }
 
-    // TODO: Implement command subscription
     pub async fn subscribe_to_commands(&self, topic: &str) -> Result<(), Box<dyn std::error::Error>> {
         info!("Subscribing to commands on: {}", topic);
         self.client.subscribe(topic, QoS::AtMostOnce).await?;
         Ok(())
     }
+
+    /// Subscribe to every command entity's command topic registered by
+    /// [`Self::publish_all_discovery`], so the dispatcher receives all of
+    /// them on the shared channel returned by [`Self::new`].
+    pub async fn subscribe_all_commands(&self, vin: &str) -> Result<(), Box<dyn std::error::Error>> {
+        for entity in vehicle_entities() {
+            if entity.has_command_topic {
+                self.subscribe_to_commands(&self.command_topic(vin, entity.object_id))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn availability_topic(config: &MqttConfig, vin: &str) -> String {
+    format!("{}/{}/availability", config.discovery_prefix, vin)
 }
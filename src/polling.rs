@@ -0,0 +1,256 @@
+// Declarative polling schedule for vehicle state signals
+//
+// Mirrors how a register-mapping bridge lets users declare which values to
+// read and on what cadence. Each configured signal gets its own interval
+// timer; on tick it builds the corresponding data request and sends it down
+// the BLE session channel for signing and transmission. Replies come back
+// on a separate channel from the BLE session task; `run_state_publisher`
+// decodes them and publishes each signal's latest value to its MQTT state
+// topic, also updating the shared asleep flag so the ticker can skip
+// signals that shouldn't wake a sleeping vehicle.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, warn};
+use prost::Message;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::MissedTickBehavior;
+
+use crate::ble_session::VehicleReply;
+use crate::config::{PollingConfig, SignalConfig};
+use crate::dispatch::VehicleCommand;
+use crate::mqtt::MqttClient;
+use crate::proto::car_server;
+use crate::proto::vcsec;
+
+/// Builds the data request for a configured signal name. A new signal needs
+/// a case here; its value reaches MQTT only if `publish_vehicle_data` or
+/// `publish_vcsec_status` also publishes it under that name.
+fn build_request(signal: &str) -> Option<VehicleCommand> {
+    match signal {
+        "battery_level" | "range" | "charge_state" | "charging" => Some(VehicleCommand::CarServer(
+            get_vehicle_data_request(car_server::get_vehicle_data::VehicleDataType::ChargeState),
+        )),
+        "interior_temp" => Some(VehicleCommand::CarServer(get_vehicle_data_request(
+            car_server::get_vehicle_data::VehicleDataType::ClimateState,
+        ))),
+        "locked" | "asleep" => Some(VehicleCommand::Vcsec(vcsec::ToVCSECMessage {
+            signed_message: None,
+            unsigned_message: Some(vcsec::UnsignedMessage {
+                informational_request: Some(vcsec::InformationRequest {
+                    information_request_type: vcsec::InformationRequestType::GetStatus as i32,
+                }),
+                ..Default::default()
+            }),
+            rke_action: None,
+        })),
+        _ => None,
+    }
+}
+
+fn get_vehicle_data_request(
+    data_type: car_server::get_vehicle_data::VehicleDataType,
+) -> car_server::Action {
+    car_server::Action {
+        action_msg: Some(car_server::action::ActionMsg::VehicleAction(
+            car_server::VehicleAction {
+                vehicle_action_msg: Some(
+                    car_server::vehicle_action::VehicleActionMsg::GetVehicleData(
+                        car_server::GetVehicleData {
+                            get_vehicle_data_type: data_type as i32,
+                        },
+                    ),
+                ),
+            },
+        )),
+    }
+}
+
+/// Runs every configured signal's ticker and feeds requests to the BLE
+/// session task on each one's cadence.
+pub struct PollingScheduler {
+    config: PollingConfig,
+    to_session: mpsc::Sender<VehicleCommand>,
+}
+
+impl PollingScheduler {
+    pub fn new(config: PollingConfig, to_session: mpsc::Sender<VehicleCommand>) -> Self {
+        PollingScheduler { config, to_session }
+    }
+
+    /// Run forever, ticking every configured signal on its own interval
+    /// (floored at `min_interval_secs`). `is_asleep` reflects the vehicle's
+    /// last known sleep state as decoded by [`run_state_publisher`], so
+    /// signals not marked `poll_while_asleep` are skipped while it's asleep
+    /// instead of waking it just to report state. `poll_now_receivers` fires
+    /// an out-of-cycle poll for every signal as soon as the BLE session task
+    /// reconnects, instead of leaving entities on stale state until their
+    /// next scheduled tick — one receiver per configured signal, in the same
+    /// order, subscribed by the caller before this task starts so no early
+    /// signal is missed.
+    pub async fn run(
+        self,
+        is_asleep: Arc<AtomicBool>,
+        poll_now_receivers: Vec<broadcast::Receiver<()>>,
+    ) {
+        let mut handles = Vec::new();
+
+        for (signal, poll_now) in self.config.signals.into_iter().zip(poll_now_receivers) {
+            let interval_secs = signal.interval_secs.max(self.config.min_interval_secs);
+            let to_session = self.to_session.clone();
+            let is_asleep = is_asleep.clone();
+
+            handles.push(tokio::spawn(async move {
+                run_signal(signal, interval_secs, to_session, is_asleep, poll_now).await;
+            }));
+        }
+
+        for handle in handles {
+            let _ = handle.await;
+        }
+    }
+}
+
+async fn run_signal(
+    signal: SignalConfig,
+    interval_secs: u64,
+    to_session: mpsc::Sender<VehicleCommand>,
+    is_asleep: Arc<AtomicBool>,
+    mut poll_now: broadcast::Receiver<()>,
+) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let forced = tokio::select! {
+            _ = ticker.tick() => false,
+            result = poll_now.recv() => {
+                match result {
+                    Ok(()) => true,
+                    // We only care that a reconnect happened, not how many;
+                    // missing a burst of them under load is fine.
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        };
+
+        if !signal.poll_while_asleep && is_asleep.load(Ordering::Relaxed) {
+            debug!("Skipping poll for {} while vehicle is asleep", signal.name);
+            continue;
+        }
+
+        // A forced poll that's actually going out jumps ahead of the
+        // ticker's own schedule; push the next scheduled tick out by a full
+        // interval so it doesn't fire again moments later for the same
+        // signal. Skipped (asleep) forced polls leave the ticker alone so
+        // sleep doesn't delay the signal's next real chance to refresh.
+        if forced {
+            ticker.reset();
+        }
+
+        let Some(request) = build_request(&signal.name) else {
+            warn!("No poll request builder registered for signal: {}", signal.name);
+            continue;
+        };
+
+        if to_session.send(request).await.is_err() {
+            warn!("BLE session task is gone, stopping poll for {}", signal.name);
+            return;
+        }
+    }
+}
+
+/// Consumes raw vehicle replies from the BLE session task, decodes them as
+/// whichever response type they parse as, and publishes the fields each
+/// configured signal cares about to its MQTT state topic. Also updates
+/// `is_asleep` from decoded VCSEC status so [`PollingScheduler::run`] can
+/// skip signals that shouldn't wake the vehicle.
+pub async fn run_state_publisher(
+    mut replies: mpsc::Receiver<VehicleReply>,
+    mqtt: Arc<MqttClient>,
+    vin: String,
+    is_asleep: Arc<AtomicBool>,
+) {
+    while let Some(reply) = replies.recv().await {
+        // Protobuf decoding silently skips unrecognized field numbers rather
+        // than erroring, so a reply of one type can "successfully" decode as
+        // an empty instance of the other. Guard against that by requiring at
+        // least one field we actually care about to be present before
+        // treating a decode as a real match, and falling through otherwise.
+        if let Ok(vehicle_data) = car_server::VehicleData::decode(reply.payload.as_slice()) {
+            if vehicle_data.charge_state.is_some() || vehicle_data.climate_state.is_some() {
+                publish_vehicle_data(&mqtt, &vin, &vehicle_data).await;
+                continue;
+            }
+        }
+
+        if let Ok(vcsec_message) = vcsec::FromVCSECMessage::decode(reply.payload.as_slice()) {
+            if vcsec_message.sub_message.is_some() {
+                publish_vcsec_status(&mqtt, &vin, &vcsec_message, &is_asleep).await;
+                continue;
+            }
+        }
+
+        warn!(
+            "Could not decode {}-byte vehicle reply as any known response type",
+            reply.payload.len()
+        );
+    }
+}
+
+async fn publish_vehicle_data(mqtt: &MqttClient, vin: &str, vehicle_data: &car_server::VehicleData) {
+    if let Some(charge_state) = &vehicle_data.charge_state {
+        publish_state(mqtt, vin, "battery_level", charge_state.battery_level.to_string()).await;
+        publish_state(mqtt, vin, "range", format!("{:.1}", charge_state.battery_range)).await;
+        publish_state(mqtt, vin, "charge_state", charge_state.charging_state.clone()).await;
+        publish_state(
+            mqtt,
+            vin,
+            "charging",
+            on_off(charge_state.charging_state == "Charging"),
+        )
+        .await;
+    }
+
+    if let Some(climate_state) = &vehicle_data.climate_state {
+        publish_state(
+            mqtt,
+            vin,
+            "interior_temp",
+            format!("{:.1}", climate_state.inside_temp),
+        )
+        .await;
+    }
+}
+
+async fn publish_vcsec_status(
+    mqtt: &MqttClient,
+    vin: &str,
+    message: &vcsec::FromVCSECMessage,
+    is_asleep: &AtomicBool,
+) {
+    let Some(vcsec::from_vcsec_message::SubMessage::VehicleStatus(status)) = &message.sub_message
+    else {
+        return;
+    };
+
+    publish_state(mqtt, vin, "locked", on_off(status.locked)).await;
+
+    let asleep = status.vehicle_sleep_status == vcsec::VehicleSleepStatus::Asleep as i32;
+    is_asleep.store(asleep, Ordering::Relaxed);
+    publish_state(mqtt, vin, "asleep", on_off(asleep)).await;
+}
+
+async fn publish_state(mqtt: &MqttClient, vin: &str, object_id: &str, payload: String) {
+    let topic = mqtt.state_topic(vin, object_id);
+    if let Err(e) = mqtt.publish_state(&topic, &payload).await {
+        warn!("Failed to publish state for {}: {}", object_id, e);
+    }
+}
+
+fn on_off(value: bool) -> String {
+    if value { "ON" } else { "OFF" }.to_string()
+}
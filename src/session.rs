@@ -0,0 +1,611 @@
+// Tesla vehicle-command authentication and session handshake
+//
+// Implements the handshake described in
+// https://github.com/teslamotors/vehicle-command: enroll this add-on's key
+// with the vehicle's VCSEC whitelist, then establish an authenticated
+// session with each command domain (VCSEC, INFOTAINMENT) by deriving a
+// shared AES-128 key via ECDH and signing outbound messages with
+// AES-128-GCM. Every message that actually goes over the wire — the
+// whitelist enrollment, the `SessionInfo` request, and signed commands — is
+// built as a `proto::universal_message::RoutableMessage`, addressed via its
+// `to_destination`/`from_destination` fields and, for signed commands,
+// carrying the AEAD nonce/tag/counter in `signature_data`.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use log::info;
+use p256::ecdh::diffie_hellman;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use prost::Message;
+use rand::RngCore;
+use sha1::{Digest, Sha1};
+
+use crate::proto::signatures;
+use crate::proto::universal_message::{self, Domain};
+
+/// How far past the (already advanced) session counter each signed command's
+/// expiration counter is set, so a command that is retried or delayed in
+/// flight doesn't get rejected by the vehicle for using a stale expiration
+/// counter. The session counter itself still advances by one per command.
+const EXPIRATION_COUNTER_WINDOW: u32 = 5;
+
+/// Default time-to-live for a signed command, in seconds.
+const DEFAULT_TTL_SECS: u8 = 5;
+
+/// This add-on's long-lived NIST P-256 key pair, used to authenticate with
+/// the vehicle. Generated once and persisted; losing it means re-enrolling
+/// via the key card.
+pub struct KeyPair {
+    private_key: SecretKey,
+    public_key: PublicKey,
+}
+
+impl KeyPair {
+    /// Generate a new random key pair.
+    pub fn generate() -> Self {
+        let private_key = SecretKey::random(&mut rand::thread_rng());
+        let public_key = private_key.public_key();
+        KeyPair {
+            private_key,
+            public_key,
+        }
+    }
+
+    /// Load a key pair from a PEM-encoded PKCS#8 private key, as persisted by
+    /// [`Self::to_pkcs8_pem`].
+    pub fn from_pkcs8_pem(pem: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let private_key = SecretKey::from_sec1_pem(pem).or_else(|_| {
+            use p256::pkcs8::DecodePrivateKey;
+            SecretKey::from_pkcs8_pem(pem)
+        })?;
+        let public_key = private_key.public_key();
+        Ok(KeyPair {
+            private_key,
+            public_key,
+        })
+    }
+
+    /// Serialize the private key as a PKCS#8 PEM for persistence across
+    /// restarts.
+    pub fn to_pkcs8_pem(&self) -> Result<String, Box<dyn std::error::Error>> {
+        use p256::pkcs8::{EncodePrivateKey, LineEnding};
+        Ok(self
+            .private_key
+            .to_pkcs8_pem(LineEnding::LF)?
+            .as_str()
+            .to_owned())
+    }
+
+    /// The uncompressed SEC1 encoding of our public key, as sent to the
+    /// vehicle during whitelist enrollment and session establishment.
+    pub fn public_key_bytes(&self) -> Vec<u8> {
+        self.public_key.to_encoded_point(false).as_bytes().to_vec()
+    }
+}
+
+/// Per-domain session state established after a successful handshake.
+#[derive(Debug, Clone)]
+pub struct DomainSession {
+    /// AES-128 key derived from ECDH with the vehicle's ephemeral public key
+    /// for this domain.
+    aes_key: [u8; 16],
+    /// Vehicle clock epoch, echoed back in every signed message's metadata.
+    epoch: Vec<u8>,
+    /// Last counter value accepted by the vehicle for this domain. Outbound
+    /// messages must use an expiration counter ahead of this.
+    counter: u32,
+}
+
+/// Default path for persisted session state, under the Home Assistant
+/// add-on's persistent `/data` volume (as opposed to `/config`, which holds
+/// user-editable options and is not guaranteed to survive an uninstall).
+pub const DEFAULT_SESSION_STATE_PATH: &str = "/data/tesla_ble_session.json";
+
+/// Tracks the handshake state for every domain we've established a session
+/// with, so counters and epochs survive across individual command calls.
+pub struct SessionManager {
+    keys: KeyPair,
+    sessions: HashMap<i32, DomainSession>,
+    /// Per-domain counters loaded from disk at startup. `establish` floors
+    /// the vehicle-reported counter against these so a restart can never
+    /// replay a counter value already used in a previous run.
+    persisted_counters: HashMap<i32, u32>,
+    /// Our routing identity, attached as `from_destination` on every
+    /// outbound `RoutableMessage` so the vehicle addresses its reply back to
+    /// us. Random per `SessionManager`; the vehicle doesn't need it to be
+    /// stable.
+    routing_address: Vec<u8>,
+    /// Whether our public key has already been added to the vehicle's VCSEC
+    /// whitelist, so we don't re-prompt for a key-card tap on every
+    /// reconnect.
+    enrolled: bool,
+}
+
+impl SessionManager {
+    pub fn new(keys: KeyPair) -> Self {
+        SessionManager {
+            keys,
+            sessions: HashMap::new(),
+            persisted_counters: HashMap::new(),
+            routing_address: random_routing_address(),
+            enrolled: false,
+        }
+    }
+
+    /// Build a manager from state persisted at `path`, generating and
+    /// persisting a fresh key pair if none exists yet. Per-domain counters
+    /// from the last run are loaded so [`Self::establish`] won't reuse a
+    /// counter value the vehicle has already accepted, and the enrollment
+    /// flag is loaded so an already-paired key isn't re-enrolled.
+    pub fn load_or_init(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let persisted = match PersistedSessionState::load(path)? {
+            Some(persisted) => persisted,
+            None => {
+                let keys = KeyPair::generate();
+                let persisted = PersistedSessionState::new(&keys)?;
+                persisted.save(path)?;
+                persisted
+            }
+        };
+
+        let keys = KeyPair::from_pkcs8_pem(&persisted.private_key_pem)?;
+        Ok(SessionManager {
+            keys,
+            sessions: HashMap::new(),
+            persisted_counters: persisted.domain_counters,
+            routing_address: random_routing_address(),
+            enrolled: persisted.enrolled,
+        })
+    }
+
+    /// Write the current per-domain counters, enrollment state, and our key
+    /// to `path`, so a future restart resumes past the last counter value
+    /// the vehicle accepted and doesn't re-enroll an already-paired key.
+    /// Safe to call before every domain for this run has (re-)established a
+    /// session: counters loaded at startup for domains not yet touched this
+    /// run are carried forward rather than dropped.
+    pub fn persist(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = PersistedSessionState::new(&self.keys)?;
+        state.enrolled = self.enrolled;
+        state.domain_counters = self.persisted_counters.clone();
+        for (&domain, session) in &self.sessions {
+            state.domain_counters.insert(domain, session.counter);
+        }
+        state.save(path)
+    }
+
+    pub fn public_key_bytes(&self) -> Vec<u8> {
+        self.keys.public_key_bytes()
+    }
+
+    pub fn is_enrolled(&self) -> bool {
+        self.enrolled
+    }
+
+    pub fn mark_enrolled(&mut self) {
+        self.enrolled = true;
+    }
+
+    /// Build the `RoutableMessage` that adds our public key to the
+    /// vehicle's VCSEC whitelist. The vehicle only accepts this if a
+    /// physical key card is tapped within a short window, so the caller
+    /// should prompt the user before sending it.
+    pub fn build_whitelist_enrollment(&self) -> universal_message::RoutableMessage {
+        let vcsec_message = whitelist_enrollment_payload(&self.keys.public_key_bytes());
+
+        universal_message::RoutableMessage {
+            to_destination: Some(domain_destination(Domain::DomainVehicleSecurity)),
+            from_destination: Some(self.our_destination()),
+            protobuf_message_as_bytes: vcsec_message.encode_to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Build the `RoutableMessage` that requests `SessionInfo` from
+    /// `domain`, carrying our public key so the vehicle can derive the same
+    /// shared secret once it replies.
+    pub fn build_session_info_request(&self, domain: Domain) -> universal_message::RoutableMessage {
+        universal_message::RoutableMessage {
+            to_destination: Some(domain_destination(domain)),
+            from_destination: Some(self.our_destination()),
+            session_info_request: Some(universal_message::SessionInfoRequest {
+                public_key: self.keys.public_key_bytes(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Parse the vehicle's `SessionInfo` reply out of a decoded
+    /// `RoutableMessage` and establish the session for `domain` from it.
+    pub fn establish_from_reply(
+        &mut self,
+        domain: Domain,
+        reply: &universal_message::RoutableMessage,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let session_info_bytes = reply
+            .session_info
+            .as_ref()
+            .ok_or("RoutableMessage reply carried no session_info")?;
+        let session_info = signatures::SessionInfo::decode(session_info_bytes.as_slice())?;
+
+        self.establish(
+            domain,
+            &session_info.public_key,
+            session_info.epoch,
+            session_info.counter,
+        )
+    }
+
+    /// Derive and store the session for `domain` from the vehicle's
+    /// `SessionInfo`: ECDH our private key with the vehicle's ephemeral
+    /// public key, then SHA-1 the shared X coordinate and take the first 16
+    /// bytes as the AES-128 key.
+    pub fn establish(
+        &mut self,
+        domain: Domain,
+        vehicle_public_key: &[u8],
+        epoch: Vec<u8>,
+        counter: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let vehicle_public = PublicKey::from_sec1_bytes(vehicle_public_key)?;
+        let private_scalar = self.keys_private_scalar();
+        let shared = diffie_hellman(&private_scalar, vehicle_public.as_affine());
+        let shared_x = shared.raw_secret_bytes();
+
+        let digest = Sha1::digest(shared_x);
+        let mut aes_key = [0u8; 16];
+        aes_key.copy_from_slice(&digest[..16]);
+
+        let floor = self
+            .persisted_counters
+            .get(&(domain as i32))
+            .copied()
+            .unwrap_or(0);
+
+        self.sessions.insert(
+            domain as i32,
+            DomainSession {
+                aes_key,
+                epoch,
+                counter: counter.max(floor),
+            },
+        );
+
+        info!("Session established for domain {:?}", domain);
+        Ok(())
+    }
+
+    fn keys_private_scalar(&self) -> p256::NonZeroScalar {
+        self.keys.private_key.to_nonzero_scalar()
+    }
+
+    pub fn has_session(&self, domain: Domain) -> bool {
+        self.sessions.contains_key(&(domain as i32))
+    }
+
+    /// Sign an outbound command payload with AES-128-GCM and return it as a
+    /// ready-to-send `RoutableMessage`: the ciphertext becomes
+    /// `protobuf_message_as_bytes`, and the nonce, counter, epoch, expiry,
+    /// and GCM tag are attached via `signature_data`.
+    pub fn sign(
+        &mut self,
+        domain: Domain,
+        vin: &str,
+        payload: &[u8],
+    ) -> Result<universal_message::RoutableMessage, Box<dyn std::error::Error>> {
+        let session = self
+            .sessions
+            .get_mut(&(domain as i32))
+            .ok_or("no established session for domain")?;
+
+        session.counter += 1;
+        let expiration_counter = session.counter + EXPIRATION_COUNTER_WINDOW;
+        let expires_at = (current_epoch_seconds() + DEFAULT_TTL_SECS as u64) as u32;
+
+        let mut nonce_bytes = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let nonce = Nonce::from_slice(&nonce_bytes);
+
+        let metadata = build_metadata(domain, vin, &session.epoch, expiration_counter, DEFAULT_TTL_SECS);
+
+        let cipher = Aes128Gcm::new_from_slice(&session.aes_key)?;
+        let ciphertext = cipher
+            .encrypt(
+                nonce,
+                Payload {
+                    msg: payload,
+                    aad: &metadata,
+                },
+            )
+            .map_err(|e| format!("AES-GCM signing failed: {e}"))?;
+
+        // The trailing 16 bytes of an AES-GCM ciphertext are the
+        // authentication tag; the remainder is the encrypted payload.
+        let tag_start = ciphertext.len() - 16;
+        let (encrypted_payload, tag) = ciphertext.split_at(tag_start);
+
+        let signature_data = signatures::SignatureData {
+            signature_type: Some(signatures::signature_data::SignatureType::AesGcmPersonalizedData(
+                signatures::AesGcmPersonalizedSignatureData {
+                    epoch: session.epoch.clone(),
+                    nonce: nonce_bytes.to_vec(),
+                    counter: expiration_counter,
+                    expires_at,
+                    tag: tag.to_vec(),
+                },
+            )),
+        };
+
+        Ok(universal_message::RoutableMessage {
+            to_destination: Some(domain_destination(domain)),
+            from_destination: Some(self.our_destination()),
+            protobuf_message_as_bytes: encrypted_payload.to_vec(),
+            signature_data: Some(signature_data),
+            ..Default::default()
+        })
+    }
+
+    fn our_destination(&self) -> universal_message::Destination {
+        universal_message::Destination {
+            sub_destination: Some(universal_message::destination::SubDestination::RoutingAddress(
+                self.routing_address.clone(),
+            )),
+        }
+    }
+
+    /// Whether `reply` is actually addressed back to us, as opposed to some
+    /// unrelated frame that happens to also decode as a `RoutableMessage`
+    /// (e.g. a stray VCSEC broadcast arriving while we're waiting for a
+    /// specific reply).
+    pub fn is_addressed_to_us(&self, reply: &universal_message::RoutableMessage) -> bool {
+        matches!(
+            &reply.to_destination,
+            Some(universal_message::Destination {
+                sub_destination: Some(universal_message::destination::SubDestination::RoutingAddress(address)),
+            }) if *address == self.routing_address
+        )
+    }
+}
+
+fn domain_destination(domain: Domain) -> universal_message::Destination {
+    universal_message::Destination {
+        sub_destination: Some(universal_message::destination::SubDestination::Domain(
+            domain as i32,
+        )),
+    }
+}
+
+fn random_routing_address() -> Vec<u8> {
+    let mut address = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut address);
+    address.to_vec()
+}
+
+fn build_metadata(
+    domain: Domain,
+    vin: &str,
+    epoch: &[u8],
+    expiration_counter: u32,
+    ttl_secs: u8,
+) -> Vec<u8> {
+    // Flat concatenation of raw fields in the fixed order the vehicle
+    // expects: signature type, domain, VIN (personalization), epoch,
+    // expiration counter, TTL. No tags or length prefixes — every field has
+    // a fixed or vehicle-known width, so none are needed.
+    let mut metadata = Vec::new();
+    metadata.push(0x01); // SIGNATURE_TYPE_AES_GCM_PERSONALIZED
+    metadata.push(domain as u8);
+    metadata.extend_from_slice(vin.as_bytes());
+    metadata.extend_from_slice(epoch);
+    metadata.extend_from_slice(&expiration_counter.to_be_bytes());
+    metadata.push(ttl_secs);
+    metadata
+}
+
+fn current_epoch_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Persisted enrollment/session state, so the add-on doesn't need the key
+/// card tapped again after a restart as long as the vehicle still trusts our
+/// public key.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PersistedSessionState {
+    pub private_key_pem: String,
+    #[serde(default)]
+    pub domain_counters: HashMap<i32, u32>,
+    #[serde(default)]
+    pub enrolled: bool,
+}
+
+impl PersistedSessionState {
+    pub fn new(keys: &KeyPair) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(PersistedSessionState {
+            private_key_pem: keys.to_pkcs8_pem()?,
+            domain_counters: HashMap::new(),
+            enrolled: false,
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// Build the VCSEC payload that adds `public_key` to the whitelist; wrapped
+/// into a `RoutableMessage` by [`SessionManager::build_whitelist_enrollment`].
+fn whitelist_enrollment_payload(public_key: &[u8]) -> crate::proto::vcsec::ToVCSECMessage {
+    use crate::proto::vcsec;
+
+    vcsec::ToVCSECMessage {
+        signed_message: None,
+        unsigned_message: Some(vcsec::UnsignedMessage {
+            whitelist_operation: Some(vcsec::WhitelistOperation {
+                sub_message: Some(
+                    vcsec::whitelist_operation::SubMessage::AddKeyToWhitelistAndAddPermissions(
+                        vcsec::PermissionChange {
+                            key: Some(vcsec::PublicKey {
+                                public_key_raw: public_key.to_vec(),
+                            }),
+                            key_role: vcsec::KeyFormFactor::KeyFormFactorCloudKey as i32,
+                            ..Default::default()
+                        },
+                    ),
+                ),
+                metadata_for_key: Some(vcsec::KeyMetadata {
+                    key_form_factor: vcsec::KeyFormFactor::KeyFormFactorCloudKey as i32,
+                }),
+            }),
+            ..Default::default()
+        }),
+        rke_action: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager_with_session(domain: Domain, floor: u32, reported_counter: u32) -> SessionManager {
+        let keys = KeyPair::generate();
+        let mut manager = SessionManager::new(keys);
+        manager.persisted_counters.insert(domain as i32, floor);
+
+        let vehicle_keys = KeyPair::generate();
+        manager
+            .establish(domain, &vehicle_keys.public_key_bytes(), vec![0xAB; 4], reported_counter)
+            .expect("establish should succeed with a valid vehicle public key");
+        manager
+    }
+
+    fn signature_counter(routable: &universal_message::RoutableMessage) -> u32 {
+        let signatures::signature_data::SignatureType::AesGcmPersonalizedData(data) = routable
+            .signature_data
+            .as_ref()
+            .expect("sign() should always attach signature_data")
+            .signature_type
+            .as_ref()
+            .expect("signature_type should always be set")
+        else {
+            panic!("expected an AES-GCM personalized signature");
+        };
+        data.counter
+    }
+
+    #[test]
+    fn establish_floors_the_vehicle_reported_counter_at_the_persisted_value() {
+        let manager = manager_with_session(Domain::DomainVehicleSecurity, 100, 5);
+        let session = manager
+            .sessions
+            .get(&(Domain::DomainVehicleSecurity as i32))
+            .unwrap();
+        assert_eq!(session.counter, 100);
+    }
+
+    #[test]
+    fn establish_keeps_the_vehicle_counter_when_it_is_already_ahead_of_the_floor() {
+        let manager = manager_with_session(Domain::DomainVehicleSecurity, 10, 50);
+        let session = manager
+            .sessions
+            .get(&(Domain::DomainVehicleSecurity as i32))
+            .unwrap();
+        assert_eq!(session.counter, 50);
+    }
+
+    #[test]
+    fn sign_opens_an_expiration_window_ahead_of_the_advanced_counter() {
+        let mut manager = manager_with_session(Domain::DomainVehicleSecurity, 0, 10);
+        let signed = manager
+            .sign(Domain::DomainVehicleSecurity, "5YJSA1E2XJF000001", b"payload")
+            .unwrap();
+
+        // establish() set counter=10; sign() advances it to 11 before opening
+        // the window, so the attached expiration counter should be
+        // 11 + EXPIRATION_COUNTER_WINDOW, not 10 + the window.
+        assert_eq!(signature_counter(&signed), 11 + EXPIRATION_COUNTER_WINDOW);
+    }
+
+    #[test]
+    fn sign_advances_the_counter_on_every_call() {
+        let mut manager = manager_with_session(Domain::DomainVehicleSecurity, 0, 10);
+        let first = manager
+            .sign(Domain::DomainVehicleSecurity, "5YJSA1E2XJF000001", b"payload")
+            .unwrap();
+        let second = manager
+            .sign(Domain::DomainVehicleSecurity, "5YJSA1E2XJF000001", b"payload")
+            .unwrap();
+
+        assert_eq!(signature_counter(&second), signature_counter(&first) + 1);
+    }
+
+    #[test]
+    fn sign_fails_without_an_established_session() {
+        let keys = KeyPair::generate();
+        let mut manager = SessionManager::new(keys);
+        assert!(manager
+            .sign(Domain::DomainInfotainment, "5YJSA1E2XJF000001", b"payload")
+            .is_err());
+    }
+
+    #[test]
+    fn build_metadata_concatenates_fields_in_order_with_no_framing() {
+        let metadata = build_metadata(Domain::DomainVehicleSecurity, "5YJSA1E2XJF000001", &[0xAA, 0xBB], 42, 5);
+
+        let mut expected = vec![0x01, Domain::DomainVehicleSecurity as u8];
+        expected.extend_from_slice(b"5YJSA1E2XJF000001");
+        expected.extend_from_slice(&[0xAA, 0xBB]);
+        expected.extend_from_slice(&42u32.to_be_bytes());
+        expected.push(5);
+
+        assert_eq!(metadata, expected);
+    }
+
+    #[test]
+    fn persist_carries_forward_counters_for_domains_not_touched_this_run() {
+        let path = std::env::temp_dir().join(format!(
+            "tesla_ble_session_persist_test_{}_{}.json",
+            std::process::id(),
+            Domain::DomainVehicleSecurity as i32
+        ));
+        let path = path.to_str().unwrap();
+
+        let mut manager = manager_with_session(Domain::DomainVehicleSecurity, 0, 7);
+        manager
+            .persisted_counters
+            .insert(Domain::DomainInfotainment as i32, 42);
+
+        manager.persist(path).expect("persist should succeed");
+        let persisted = PersistedSessionState::load(path)
+            .expect("load should succeed")
+            .expect("a state file should have been written");
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(
+            persisted.domain_counters.get(&(Domain::DomainVehicleSecurity as i32)),
+            Some(&7),
+            "the domain established this run should use its session counter"
+        );
+        assert_eq!(
+            persisted.domain_counters.get(&(Domain::DomainInfotainment as i32)),
+            Some(&42),
+            "a domain not yet re-established this run should keep its persisted counter"
+        );
+    }
+}
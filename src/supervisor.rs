@@ -0,0 +1,117 @@
+// BLE reconnection supervisor
+//
+// Wraps `BleAdapter` with persistent vehicle identity and a reconnect loop:
+// once we've scanned and found the vehicle, later reconnects go straight to
+// its known address instead of rescanning. On disconnect we publish
+// `offline` to the HA availability topic and retry with capped exponential
+// backoff until the link (and, via the caller's `on_connected` hook, the
+// session handshake) is re-established.
+
+use std::time::Duration;
+
+use bluer::Address;
+use futures::StreamExt;
+use log::{info, warn};
+
+use crate::bluetooth::{BleAdapter, DiscoveredVehicle, VehicleLink};
+use crate::mqtt::{MqttClient, AVAILABLE_OFFLINE, AVAILABLE_ONLINE};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Supervises the BLE link to a single vehicle across disconnects, reusing
+/// its resolved address once known instead of rescanning every time.
+pub struct VehicleSupervisor {
+    adapter: BleAdapter,
+    vin: String,
+    known_address: Option<Address>,
+}
+
+impl VehicleSupervisor {
+    pub fn new(adapter: BleAdapter, vin: String) -> Self {
+        VehicleSupervisor {
+            adapter,
+            vin,
+            known_address: None,
+        }
+    }
+
+    /// Connect to the vehicle, scanning for it only if we don't already know
+    /// its address from a previous successful connection.
+    async fn connect(&mut self) -> Result<VehicleLink, Box<dyn std::error::Error>> {
+        let vehicle = match self.known_address {
+            Some(address) => DiscoveredVehicle { address },
+            None => {
+                let found = self.adapter.scan_for_devices(&self.vin).await?;
+                self.known_address = Some(found.address);
+                found
+            }
+        };
+
+        self.adapter.connect_to_device(&vehicle).await
+    }
+
+    /// Run the supervised connection loop forever: connect, hand the fresh
+    /// link to `on_connected` (which should re-run the session handshake),
+    /// wait for the device to disconnect, then reconnect with backoff.
+    /// Publishes HA availability across every transition.
+    pub async fn run(
+        &mut self,
+        mqtt: &MqttClient,
+        mut on_connected: impl FnMut(VehicleLink),
+    ) -> ! {
+        let mut backoff = INITIAL_BACKOFF;
+
+        loop {
+            match self.connect().await {
+                Ok(link) => {
+                    info!("Connected to vehicle {}", link.address());
+                    backoff = INITIAL_BACKOFF;
+
+                    if let Err(e) = mqtt.publish_availability(&self.vin, AVAILABLE_ONLINE).await {
+                        warn!("Failed to publish availability: {}", e);
+                    }
+
+                    let address = link.address();
+                    on_connected(link);
+                    self.wait_for_disconnect(address).await;
+                    info!("Vehicle {} disconnected", address);
+                }
+                Err(e) => {
+                    warn!("Failed to connect to vehicle: {}", e);
+                }
+            }
+
+            if let Err(e) = mqtt.publish_availability(&self.vin, AVAILABLE_OFFLINE).await {
+                warn!("Failed to publish availability: {}", e);
+            }
+
+            info!("Reconnecting to vehicle in {:?}", backoff);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Block until the adapter reports the device at `address` as
+    /// disconnected, or the device vanishes from the adapter entirely (the
+    /// vehicle went out of range or to sleep).
+    async fn wait_for_disconnect(&self, address: Address) {
+        let device = match self.adapter.device(address) {
+            Ok(device) => device,
+            Err(_) => return,
+        };
+
+        let mut events = match device.events().await {
+            Ok(events) => events,
+            Err(_) => return,
+        };
+
+        while let Some(event) = events.next().await {
+            if let bluer::DeviceEvent::PropertyChanged(bluer::DeviceProperty::Connected(false)) =
+                event
+            {
+                return;
+            }
+        }
+    }
+}